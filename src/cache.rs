@@ -0,0 +1,212 @@
+use std::collections::{HashMap, VecDeque};
+use std::io::{Read, Seek, SeekFrom};
+use std::rc::Rc;
+
+use tempfile::NamedTempFile;
+
+/// Fully decompressed contents of a single archive member. DEFLATE streams
+/// aren't seekable, so instead of re-skipping from zero on every backward
+/// seek we decompress once and serve reads as direct slices.
+pub enum CachedContent {
+    Memory(Vec<u8>),
+    Spilled { file: NamedTempFile, size: u64 },
+}
+
+impl CachedContent {
+    pub fn len(&self) -> u64 {
+        match self {
+            CachedContent::Memory(buf) => buf.len() as u64,
+            CachedContent::Spilled { size, .. } => *size,
+        }
+    }
+
+    pub fn read_at(&self, offset: u64, size: u32) -> Vec<u8> {
+        match self {
+            CachedContent::Memory(buf) => {
+                let offset = (offset as usize).min(buf.len());
+                let end = offset.saturating_add(size as usize).min(buf.len());
+                buf[offset..end].to_vec()
+            }
+            CachedContent::Spilled { file, size: len } => {
+                let offset = offset.min(*len);
+                let to_read = (size as u64).min(len - offset) as usize;
+
+                let Ok(mut handle) = file.reopen() else {
+                    return vec![];
+                };
+
+                if handle.seek(SeekFrom::Start(offset)).is_err() {
+                    return vec![];
+                }
+
+                let mut buf = vec![0; to_read];
+                let read = handle.read(&mut buf).unwrap_or(0);
+                buf.truncate(read);
+                buf
+            }
+        }
+    }
+}
+
+/// Global LRU of decompressed archive members, keyed by inode and bounded
+/// by total cached bytes. Concurrent `open` handles to the same file share
+/// one decompression; cold files are evicted first.
+pub struct DecompressedCache {
+    entries: HashMap<u64, Rc<CachedContent>>,
+    order: VecDeque<u64>,
+    total_bytes: u64,
+    max_bytes: u64,
+}
+
+impl DecompressedCache {
+    pub fn new(max_bytes: u64) -> Self {
+        Self {
+            entries: HashMap::new(),
+            order: VecDeque::new(),
+            total_bytes: 0,
+            max_bytes,
+        }
+    }
+
+    pub fn get_or_insert_with(
+        &mut self,
+        ino: u64,
+        decompress: impl FnOnce() -> CachedContent,
+    ) -> Rc<CachedContent> {
+        if let Some(entry) = self.entries.get(&ino).cloned() {
+            self.touch(ino);
+            return entry;
+        }
+
+        let content = Rc::new(decompress());
+
+        // A member larger than the whole budget can never coexist with
+        // anything else in the cache, so inserting it would just have
+        // `evict_if_needed` remove it again before this call returns —
+        // turning every subsequent read of it into a fresh decompression.
+        // Serve it once, uncached, instead.
+        if content.len() > self.max_bytes {
+            return content;
+        }
+
+        self.total_bytes += content.len();
+        self.entries.insert(ino, content.clone());
+        self.order.push_back(ino);
+
+        self.evict_if_needed();
+
+        content
+    }
+
+    fn touch(&mut self, ino: u64) {
+        if let Some(pos) = self.order.iter().position(|&x| x == ino) {
+            self.order.remove(pos);
+        }
+        self.order.push_back(ino);
+    }
+
+    fn evict_if_needed(&mut self) {
+        while self.total_bytes > self.max_bytes {
+            let Some(oldest) = self.order.pop_front() else {
+                break;
+            };
+
+            if let Some(entry) = self.entries.remove(&oldest) {
+                self.total_bytes = self.total_bytes.saturating_sub(entry.len());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use super::*;
+
+    fn memory(bytes: &[u8]) -> CachedContent {
+        CachedContent::Memory(bytes.to_vec())
+    }
+
+    #[test]
+    fn reuses_a_cached_entry_without_recomputing() {
+        let mut cache = DecompressedCache::new(1024);
+        let calls = Cell::new(0);
+
+        let decompress = || {
+            calls.set(calls.get() + 1);
+            memory(b"hello")
+        };
+
+        cache.get_or_insert_with(1, decompress);
+        cache.get_or_insert_with(1, decompress);
+
+        assert_eq!(calls.get(), 1);
+    }
+
+    #[test]
+    fn evicts_the_least_recently_used_entry_once_over_budget() {
+        let mut cache = DecompressedCache::new(10);
+
+        cache.get_or_insert_with(1, || memory(&[0; 6]));
+        cache.get_or_insert_with(2, || memory(&[0; 6]));
+
+        assert!(cache.entries.contains_key(&2));
+        assert!(!cache.entries.contains_key(&1));
+    }
+
+    #[test]
+    fn touching_an_entry_protects_it_from_the_next_eviction() {
+        let mut cache = DecompressedCache::new(10);
+
+        cache.get_or_insert_with(1, || memory(&[0; 6]));
+        cache.get_or_insert_with(2, || memory(&[0; 2]));
+        // Re-fetching 1 should bump it ahead of 2 in eviction order.
+        cache.get_or_insert_with(1, || memory(&[0; 6]));
+        cache.get_or_insert_with(3, || memory(&[0; 6]));
+
+        assert!(cache.entries.contains_key(&1));
+        assert!(!cache.entries.contains_key(&2));
+    }
+
+    #[test]
+    fn serves_an_oversized_entry_uncached_instead_of_evicting_everything() {
+        let mut cache = DecompressedCache::new(4);
+        let calls = Cell::new(0);
+
+        let decompress = || {
+            calls.set(calls.get() + 1);
+            memory(&[0; 8])
+        };
+
+        let first = cache.get_or_insert_with(1, decompress);
+        let second = cache.get_or_insert_with(1, decompress);
+
+        assert_eq!(first.len(), 8);
+        assert_eq!(second.len(), 8);
+        assert_eq!(calls.get(), 2, "an over-budget member is never cached");
+        assert!(cache.entries.is_empty());
+    }
+
+    #[test]
+    fn reads_in_memory_content_at_an_offset() {
+        let content = memory(b"0123456789");
+        assert_eq!(content.read_at(3, 4), b"3456".to_vec());
+        assert_eq!(content.read_at(8, 10), b"89".to_vec());
+        assert_eq!(content.read_at(20, 4), Vec::<u8>::new());
+    }
+
+    #[test]
+    fn reads_spilled_content_back_from_disk_at_an_offset() {
+        use std::io::Write;
+
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all(b"0123456789").unwrap();
+
+        let content = CachedContent::Spilled { file, size: 10 };
+
+        assert_eq!(content.read_at(3, 4), b"3456".to_vec());
+        assert_eq!(content.read_at(8, 10), b"89".to_vec());
+        assert_eq!(content.read_at(20, 4), Vec::<u8>::new());
+    }
+}
@@ -0,0 +1,314 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{FSEntry, FSEntryInfo, FileInfo, Source};
+
+/// Metadata used to decide whether a cached archive entry is still valid,
+/// without re-reading its central directory.
+#[derive(Debug, Serialize, Deserialize)]
+struct ArchiveMeta {
+    path: PathBuf,
+    size: u64,
+    mtime: SystemTime,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct OwnedVersion {
+    time: SystemTime,
+    size: u64,
+    mode: u32,
+    crc32: u32,
+    compressed_size: u64,
+    rdev: u32,
+    archive: PathBuf,
+    file_id: usize,
+}
+
+/// Owned twin of [`FSEntry`] that can be serialized to disk. `FSEntry`
+/// borrows `&'a Path` from the sources vector, which doesn't round-trip
+/// through serde, so every archive path is stored owned here instead.
+///
+/// `Leaf` covers every non-directory `FSEntry` variant (file, symlink,
+/// device node, ...) since the Unix mode bits stored on each version are
+/// enough to pick the right variant back out on rebuild.
+#[derive(Debug, Serialize, Deserialize)]
+enum OwnedFSEntry {
+    Leaf {
+        ino: u64,
+        versions: Vec<OwnedVersion>,
+    },
+    Directory {
+        ino: u64,
+        entries: HashMap<String, OwnedFSEntry>,
+    },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct IndexFile {
+    archives: Vec<ArchiveMeta>,
+    // Whether `root` includes the `.snapshots` tree; an index built with
+    // one `--versions` setting must not be reused for a mount with the
+    // other, or the flag would silently stop doing anything.
+    versions_mode: bool,
+    root: OwnedFSEntry,
+}
+
+fn to_owned_entry(entry: &FSEntry) -> OwnedFSEntry {
+    match entry {
+        FSEntry::Directory { ino, entries } => OwnedFSEntry::Directory {
+            ino: *ino,
+            entries: entries
+                .iter()
+                .map(|(name, entry)| (name.clone(), to_owned_entry(entry)))
+                .collect(),
+        },
+        FSEntry::File { ino, versions }
+        | FSEntry::Symlink { ino, versions }
+        | FSEntry::CharDevice { ino, versions }
+        | FSEntry::BlockDevice { ino, versions }
+        | FSEntry::NamedPipe { ino, versions }
+        | FSEntry::Socket { ino, versions } => OwnedFSEntry::Leaf {
+            ino: *ino,
+            versions: versions
+                .iter()
+                .map(|v| OwnedVersion {
+                    time: v.info.time,
+                    size: v.info.size,
+                    mode: v.info.mode,
+                    crc32: v.info.crc32,
+                    compressed_size: v.info.compressed_size,
+                    rdev: v.info.rdev,
+                    archive: v.source.archive.to_owned(),
+                    file_id: v.source.file_id,
+                })
+                .collect(),
+        },
+    }
+}
+
+fn archive_meta(path: &Path) -> Option<ArchiveMeta> {
+    let meta = fs::metadata(path).ok()?;
+    Some(ArchiveMeta {
+        path: path.to_owned(),
+        size: meta.len(),
+        mtime: meta.modified().ok()?,
+    })
+}
+
+/// Reconstructs an `FSEntry` tree from its owned form, dropping any entry
+/// whose source archive is missing, resized, or stale rather than failing
+/// the whole load.
+fn rebuild<'a>(
+    owned: OwnedFSEntry,
+    valid_archives: &HashMap<PathBuf, &'a Path>,
+) -> Option<FSEntry<'a>> {
+    match owned {
+        OwnedFSEntry::Directory { ino, entries } => {
+            let entries = entries
+                .into_iter()
+                .filter_map(|(name, entry)| rebuild(entry, valid_archives).map(|e| (name, e)))
+                .collect();
+            Some(FSEntry::Directory { ino, entries })
+        }
+        OwnedFSEntry::Leaf { ino, versions } => {
+            let versions: Vec<FSEntryInfo> = versions
+                .into_iter()
+                .filter_map(|v| {
+                    let archive = *valid_archives.get(&v.archive)?;
+                    Some(FSEntryInfo {
+                        info: FileInfo {
+                            time: v.time,
+                            size: v.size,
+                            mode: v.mode,
+                            crc32: v.crc32,
+                            compressed_size: v.compressed_size,
+                            rdev: v.rdev,
+                        },
+                        source: Source {
+                            archive,
+                            file_id: v.file_id,
+                        },
+                    })
+                })
+                .collect();
+
+            if versions.is_empty() {
+                return None;
+            }
+
+            Some(FSEntry::leaf(ino, versions))
+        }
+    }
+}
+
+/// Serializes `root` (plus the sources it was built from and the
+/// `--versions` setting it was built with) to a zstd-compressed index at
+/// `path`.
+pub fn save(path: &Path, root: &FSEntry, sources: &[PathBuf], versions_mode: bool) -> Result<(), ()> {
+    let archives = sources.iter().filter_map(|p| archive_meta(p)).collect();
+    let index = IndexFile {
+        archives,
+        versions_mode,
+        root: to_owned_entry(root),
+    };
+
+    let encoded = bincode::serialize(&index).map_err(|_| ())?;
+    let compressed = zstd::stream::encode_all(&*encoded, 0).map_err(|_| ())?;
+    fs::write(path, compressed).map_err(|_| ())
+}
+
+/// Loads a previously saved index from `path`, validating each source
+/// archive's path, size, and mtime against `sources` on disk. Returns
+/// `None` if the index is missing, corrupt, unreadable, was built with a
+/// different `--versions` setting than `versions_mode`, or if `sources`
+/// contains an archive the index has never seen (a new backup added since
+/// the last scan) — the caller then falls back to a full rescan rather
+/// than silently mounting a stale or mismatched tree. Entries whose
+/// archive is present but no longer matches are simply dropped from the
+/// result.
+pub fn load<'a>(path: &Path, sources: &'a [PathBuf], versions_mode: bool) -> Option<FSEntry<'a>> {
+    let compressed = fs::read(path).ok()?;
+    let encoded = zstd::stream::decode_all(&*compressed).ok()?;
+    let index: IndexFile = bincode::deserialize(&encoded).ok()?;
+
+    if index.versions_mode != versions_mode {
+        return None;
+    }
+
+    let has_new_archive = sources
+        .iter()
+        .any(|source| !index.archives.iter().any(|meta| &meta.path == source));
+    if has_new_archive {
+        return None;
+    }
+
+    let mut valid_archives = HashMap::new();
+    for meta in &index.archives {
+        let Some(current) = archive_meta(&meta.path) else {
+            continue;
+        };
+
+        if current.size != meta.size || current.mtime != meta.mtime {
+            continue;
+        }
+
+        let Some(source_path) = sources.iter().find(|p| **p == meta.path) else {
+            continue;
+        };
+
+        valid_archives.insert(meta.path.clone(), source_path.as_path());
+    }
+
+    rebuild(index.root, &valid_archives)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use tempfile::NamedTempFile;
+
+    use super::*;
+    use crate::DEFAULT_FILE_MODE;
+
+    /// A one-entry tree: a root directory containing `name` as a file with
+    /// a single version sourced from `archive`.
+    fn tree_with_one_file<'a>(name: &str, archive: &'a Path) -> FSEntry<'a> {
+        let mut entries = HashMap::new();
+        entries.insert(
+            name.to_owned(),
+            FSEntry::leaf(
+                1,
+                vec![FSEntryInfo {
+                    info: FileInfo {
+                        time: SystemTime::now(),
+                        size: 0,
+                        mode: DEFAULT_FILE_MODE,
+                        crc32: 0,
+                        compressed_size: 0,
+                        rdev: 0,
+                    },
+                    source: Source {
+                        archive,
+                        file_id: 0,
+                    },
+                }],
+            ),
+        );
+        FSEntry::Directory { ino: 0, entries }
+    }
+
+    fn entry_names(root: &FSEntry) -> Vec<String> {
+        let FSEntry::Directory { entries, .. } = root else {
+            panic!("expected a directory");
+        };
+        let mut names: Vec<String> = entries.keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn load_returns_none_for_missing_index_file() {
+        let missing = PathBuf::from("/nonexistent/winbackup-index.zst");
+        assert!(load(&missing, &[], false).is_none());
+    }
+
+    #[test]
+    fn load_returns_none_when_versions_mode_differs() {
+        let index_file = NamedTempFile::new().unwrap();
+        let root = FSEntry::dir(0);
+
+        save(index_file.path(), &root, &[], true).unwrap();
+
+        assert!(load(index_file.path(), &[], false).is_none());
+    }
+
+    #[test]
+    fn load_returns_none_when_sources_include_an_unseen_archive() {
+        let index_file = NamedTempFile::new().unwrap();
+        let archive_a = NamedTempFile::new().unwrap();
+        let archive_b = NamedTempFile::new().unwrap();
+        let sources_at_save = vec![archive_a.path().to_owned()];
+        let root = tree_with_one_file("a.bin", archive_a.path());
+
+        save(index_file.path(), &root, &sources_at_save, false).unwrap();
+
+        let sources_at_load = vec![archive_a.path().to_owned(), archive_b.path().to_owned()];
+        assert!(load(index_file.path(), &sources_at_load, false).is_none());
+    }
+
+    #[test]
+    fn load_keeps_entries_whose_archive_is_unchanged() {
+        let index_file = NamedTempFile::new().unwrap();
+        let mut archive = NamedTempFile::new().unwrap();
+        archive.write_all(b"original contents").unwrap();
+        let sources = vec![archive.path().to_owned()];
+        let root = tree_with_one_file("a.bin", archive.path());
+
+        save(index_file.path(), &root, &sources, false).unwrap();
+
+        let loaded = load(index_file.path(), &sources, false).unwrap();
+        assert_eq!(entry_names(&loaded), vec!["a.bin".to_string()]);
+    }
+
+    #[test]
+    fn load_drops_entries_whose_archive_changed_since_saving() {
+        let index_file = NamedTempFile::new().unwrap();
+        let mut archive = NamedTempFile::new().unwrap();
+        archive.write_all(b"original contents").unwrap();
+        let sources = vec![archive.path().to_owned()];
+        let root = tree_with_one_file("a.bin", archive.path());
+
+        save(index_file.path(), &root, &sources, false).unwrap();
+
+        // Change the archive's size after the index was saved.
+        archive.write_all(b" plus more").unwrap();
+
+        let loaded = load(index_file.path(), &sources, false).unwrap();
+        assert!(entry_names(&loaded).is_empty());
+    }
+}
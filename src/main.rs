@@ -1,7 +1,4 @@
-#![feature(entry_insert)]
-
 use std::collections::HashMap;
-use std::collections::hash_map::Entry;
 use std::env;
 use std::ffi::OsStr;
 use std::fs;
@@ -11,23 +8,73 @@ use std::time::{SystemTime, Duration, UNIX_EPOCH};
 
 use fuser::{
     FileAttr, FileType, Filesystem, MountOption, ReplyAttr, ReplyData, ReplyDirectory, ReplyEmpty,
-    ReplyEntry, ReplyOpen, Request,
+    ReplyEntry, ReplyOpen, ReplyXattr, Request,
 };
-use indicatif::ProgressIterator;
-use libc::ENOENT;
-use ouroboros::self_referencing;
+use indicatif::ProgressBar;
+use libc::{EINVAL, ENODATA, ENOENT, ERANGE};
+use rayon::prelude::*;
 use zip::ZipArchive;
-use zip::read::ZipFile;
 
+mod cache;
+mod index;
+
+use cache::{CachedContent, DecompressedCache};
 
 const BLOCK_SIZE: u64 = 64 * 1024;
 const TTL: Duration = Duration::from_secs(60 * 60 * 24); // Just a big number
 
-
-#[derive(Debug)]
+// Default bound on total decompressed bytes kept in the read cache.
+const DEFAULT_CACHE_BYTES: u64 = 512 * 1024 * 1024;
+// Members at or below this size are cached in memory; larger ones spill to a temp file.
+const DEFAULT_MEMORY_THRESHOLD: u64 = 16 * 1024 * 1024;
+// Used when a ZIP entry carries no Unix external attributes at all.
+const DEFAULT_FILE_MODE: u32 = 0o100_644;
+
+// POSIX file-type bits within `st_mode`, as stored in a ZIP entry's Unix external attributes.
+const S_IFMT: u32 = 0o170_000;
+const S_IFLNK: u32 = 0o120_000;
+const S_IFSOCK: u32 = 0o140_000;
+const S_IFBLK: u32 = 0o060_000;
+const S_IFCHR: u32 = 0o020_000;
+const S_IFIFO: u32 = 0o010_000;
+
+// Header ID of the Info-ZIP/PKWARE Unix extra field ("UX") within a ZIP
+// entry's extra data, which for char/block device files carries the
+// device's major/minor numbers after its fixed atime/mtime/uid/gid header.
+const UNIX_EXTRA_FIELD_TAG: u16 = 0x5855;
+const UNIX_EXTRA_FIELD_FIXED_LEN: usize = 4 + 4 + 2 + 2; // atime, mtime, uid, gid
+
+// Read-only xattrs exposing archive provenance for a mounted entry.
+const XATTR_ARCHIVE: &str = "user.winbackup.archive";
+const XATTR_FILE_ID: &str = "user.winbackup.file_id";
+const XATTR_MTIME: &str = "user.winbackup.mtime";
+const XATTR_CRC32: &str = "user.winbackup.crc32";
+const XATTR_COMPRESSED_SIZE: &str = "user.winbackup.compressed_size";
+const XATTR_NAMES: &[&str] = &[
+    XATTR_ARCHIVE,
+    XATTR_FILE_ID,
+    XATTR_MTIME,
+    XATTR_CRC32,
+    XATTR_COMPRESSED_SIZE,
+];
+
+
+#[derive(Debug, Clone, Copy)]
 struct FileInfo {
     time: SystemTime,
     size: u64,
+    // Full `st_mode`-style bits read from the ZIP entry's Unix external
+    // attributes: file-type bits (`S_IFMT`) plus permission bits.
+    mode: u32,
+    // CRC-32 and compressed size from the ZIP central directory, surfaced
+    // read-only via xattrs for provenance auditing.
+    crc32: u32,
+    compressed_size: u64,
+    // Device major/minor, packed the traditional `(major << 8) | minor`
+    // way, parsed from the Unix extra field. Only meaningful for
+    // `CharDevice`/`BlockDevice` entries; 0 ("unknown") otherwise or when
+    // the archive didn't record one.
+    rdev: u32,
 }
 
 #[derive(Debug)]
@@ -37,13 +84,13 @@ struct File {
     info: FileInfo,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 struct Source<'a> {
     archive: &'a Path,
     file_id: usize,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 struct FSEntryInfo<'a> {
     info: FileInfo,
     source: Source<'a>,
@@ -53,21 +100,119 @@ struct FSEntryInfo<'a> {
 enum FSEntry<'a> {
     File {
         ino: u64,
-        info: FSEntryInfo<'a>,
+        // Every archive version of this path, oldest first; `.last()` is
+        // the one exposed as the file's regular contents.
+        versions: Vec<FSEntryInfo<'a>>,
     },
     Directory {
         ino: u64,
         entries: HashMap<String, FSEntry<'a>>,
     },
+    Symlink {
+        ino: u64,
+        versions: Vec<FSEntryInfo<'a>>,
+    },
+    CharDevice {
+        ino: u64,
+        versions: Vec<FSEntryInfo<'a>>,
+    },
+    BlockDevice {
+        ino: u64,
+        versions: Vec<FSEntryInfo<'a>>,
+    },
+    NamedPipe {
+        ino: u64,
+        versions: Vec<FSEntryInfo<'a>>,
+    },
+    Socket {
+        ino: u64,
+        versions: Vec<FSEntryInfo<'a>>,
+    },
 }
 
-impl FSEntry<'_> {
+impl<'a> FSEntry<'a> {
     fn dir(ino: u64) -> Self {
         Self::Directory {
             ino,
             entries: HashMap::new(),
         }
     }
+
+    /// Builds the leaf entry for a path's versions, picking the variant
+    /// from the Unix file-type bits (`S_IFMT`) of the most recent version.
+    /// Every version of a path is expected to share the same type.
+    fn leaf(ino: u64, versions: Vec<FSEntryInfo<'a>>) -> Self {
+        let mode = versions
+            .last()
+            .expect("a leaf entry always has at least one version")
+            .info
+            .mode;
+
+        match mode & S_IFMT {
+            S_IFLNK => FSEntry::Symlink { ino, versions },
+            S_IFCHR => FSEntry::CharDevice { ino, versions },
+            S_IFBLK => FSEntry::BlockDevice { ino, versions },
+            S_IFIFO => FSEntry::NamedPipe { ino, versions },
+            S_IFSOCK => FSEntry::Socket { ino, versions },
+            _ => FSEntry::File { ino, versions },
+        }
+    }
+
+    fn ino(&self) -> u64 {
+        match self {
+            FSEntry::File { ino, .. }
+            | FSEntry::Directory { ino, .. }
+            | FSEntry::Symlink { ino, .. }
+            | FSEntry::CharDevice { ino, .. }
+            | FSEntry::BlockDevice { ino, .. }
+            | FSEntry::NamedPipe { ino, .. }
+            | FSEntry::Socket { ino, .. } => *ino,
+        }
+    }
+
+    /// The version exposed as this entry's contents (the most recent
+    /// one). Panics if called on a `Directory`.
+    fn latest(&self) -> &FSEntryInfo<'a> {
+        match self {
+            FSEntry::File { versions, .. }
+            | FSEntry::Symlink { versions, .. }
+            | FSEntry::CharDevice { versions, .. }
+            | FSEntry::BlockDevice { versions, .. }
+            | FSEntry::NamedPipe { versions, .. }
+            | FSEntry::Socket { versions, .. } => versions
+                .last()
+                .expect("a leaf entry always has at least one version"),
+            FSEntry::Directory { .. } => panic!("latest() called on a directory"),
+        }
+    }
+}
+
+/// Scans a ZIP entry's raw extra field for a Unix extra field (`0x5855`)
+/// carrying device major/minor numbers, and packs them the traditional
+/// `(major << 8) | minor` way. Returns `0` if the extra field is absent,
+/// truncated, or simply doesn't contain that record — the entry is still
+/// mounted as a device node, it just reports no major/minor.
+fn unix_rdev_from_extra(extra: &[u8]) -> u32 {
+    let mut data = extra;
+
+    while let [tag_lo, tag_hi, size_lo, size_hi, rest @ ..] = data {
+        let tag = u16::from_le_bytes([*tag_lo, *tag_hi]);
+        let size = u16::from_le_bytes([*size_lo, *size_hi]) as usize;
+
+        let Some(body) = rest.get(..size) else { break };
+
+        if tag == UNIX_EXTRA_FIELD_TAG {
+            if let Some(device) = body.get(UNIX_EXTRA_FIELD_FIXED_LEN..UNIX_EXTRA_FIELD_FIXED_LEN + 8) {
+                let major = u32::from_le_bytes(device[0..4].try_into().unwrap());
+                let minor = u32::from_le_bytes(device[4..8].try_into().unwrap());
+                return (major << 8) | (minor & 0xff);
+            }
+        }
+
+        data = &rest[size..];
+    }
+
+    0
 }
 
 struct WinbackupTreeBuilder {
@@ -96,6 +241,8 @@ impl WinbackupTreeBuilder {
 
             let name = self.decode_filename(file.name_raw());
             let time = file.last_modified().to_time().unwrap().into();
+            let mode = file.unix_mode().unwrap_or(DEFAULT_FILE_MODE);
+            let rdev = unix_rdev_from_extra(file.extra_data());
 
             files.push(File {
                 file_id: i,
@@ -103,6 +250,10 @@ impl WinbackupTreeBuilder {
                 info: FileInfo {
                     time,
                     size: file.size(),
+                    mode,
+                    crc32: file.crc32(),
+                    compressed_size: file.compressed_size(),
+                    rdev,
                 },
             });
         }
@@ -110,14 +261,34 @@ impl WinbackupTreeBuilder {
         Ok(files)
     }
 
-    fn parse_multiple_archives<'a>(&self, sources: &'a [PathBuf]) -> FSEntry<'a> {
-        let mut files: HashMap<String, Vec<FSEntryInfo>> = HashMap::new();
+    /// Builds the filesystem tree from every source archive. When
+    /// `versions_mode` is set, each archive's own copy of every path it
+    /// holds (the newest copy included) is additionally exposed under
+    /// `.snapshots/<archive-stem>~<n>/<path>`, where `<n>` is the archive's
+    /// position among the contributing archives sorted by path, instead of
+    /// older copies being discarded.
+    ///
+    /// The expensive part — reading each archive's central directory — is
+    /// fanned out across cores with rayon; only the merge into the tree
+    /// below stays single-threaded and deterministic.
+    fn parse_multiple_archives<'a>(&self, sources: &'a [PathBuf], versions_mode: bool) -> FSEntry<'a> {
+        let pb = ProgressBar::new(sources.len() as u64);
+
+        let per_archive: Vec<(&'a Path, Vec<File>)> = sources
+            .par_iter()
+            .map(|archive| {
+                let infos = self.rough_file_info(archive).unwrap_or_default();
+                pb.inc(1);
+                (archive.as_path(), infos)
+            })
+            .collect();
+
+        pb.finish_and_clear();
 
-        for archive in sources.iter().progress() {
-            let Ok(infos) = self.rough_file_info(archive) else {
-                continue
-            };
+        let mut files: HashMap<String, Vec<FSEntryInfo>> = HashMap::new();
+        let mut by_archive: HashMap<&'a Path, Vec<(String, FSEntryInfo<'a>)>> = HashMap::new();
 
+        for (archive, infos) in per_archive {
             for file in infos {
                 let entry = FSEntryInfo {
                     info: file.info,
@@ -127,60 +298,92 @@ impl WinbackupTreeBuilder {
                     },
                 };
 
-                if let Some(f) = files.get_mut(&file.path) {
-                    f.push(entry);
-                } else {
-                    files.insert(file.path, vec![entry]);
+                if versions_mode {
+                    by_archive.entry(archive).or_default().push((file.path.clone(), entry));
                 }
+
+                files.entry(file.path).or_default().push(entry);
             }
         }
 
         let mut root = FSEntry::dir(1);
-
         let mut ino_counter = 2;
-        for (path, info) in files {
-            let Some(info) = info.into_iter().max_by_key(|x| x.info.time) else { continue };
-
-            let Some((path, name)) = path.rsplit_once('\\') else { continue };
 
-            let mut prev_dir = &mut root;
+        // Paths are sorted so inode assignment doesn't depend on HashMap
+        // iteration order.
+        let mut files: Vec<(String, Vec<FSEntryInfo>)> = files.into_iter().collect();
+        files.sort_by(|a, b| a.0.cmp(&b.0));
 
-            for dir_name in path.split('\\') {
-                let FSEntry::Directory{ entries, .. } = prev_dir else { break };
+        for (path, mut versions) in files {
+            versions.sort_by_key(|v| v.info.time);
+            insert_at_path(&mut root, &mut ino_counter, &path, |ino| FSEntry::leaf(ino, versions));
+        }
 
-                if !entries.contains_key(dir_name) {
-                    entries.insert(dir_name.to_owned(), FSEntry::dir(ino_counter));
-                    ino_counter += 1;
+        if versions_mode {
+            // Archives are sorted so the `index` suffix below is stable
+            // across runs rather than depending on HashMap iteration order.
+            let mut archives: Vec<&Path> = by_archive.keys().copied().collect();
+            archives.sort();
+
+            for (index, archive) in archives.into_iter().enumerate() {
+                let entries = by_archive.remove(archive).unwrap();
+                let stem = archive.file_stem().and_then(OsStr::to_str).unwrap_or("archive");
+                // Suffixed with the archive's position in the sorted list
+                // so two archives sharing a stem (e.g. `day1/C.zip` and
+                // `day2/C.zip`) get distinct, non-colliding snapshot roots.
+                let snapshot_root = format!("{stem}~{index}");
+
+                for (path, version) in entries {
+                    let snapshot_path = format!(".snapshots\\{snapshot_root}\\{path}");
+                    insert_at_path(&mut root, &mut ino_counter, &snapshot_path, |ino| {
+                        FSEntry::leaf(ino, vec![version])
+                    });
                 }
-
-                prev_dir = entries.get_mut(dir_name).unwrap();
             }
-
-            let FSEntry::Directory{ entries, .. } = prev_dir else { continue };
-            entries.insert(
-                name.to_owned(),
-                FSEntry::File {
-                    ino: ino_counter,
-                    info,
-                },
-            );
-            ino_counter += 1;
         }
 
         root
     }
 }
 
-fn build_filesystem_map<'a>(root: &'a FSEntry, ino_to_entry: &mut HashMap<u64, &'a FSEntry<'a>>) {
-    match root {
-        FSEntry::Directory { ino, entries, .. } => {
-            ino_to_entry.insert(*ino, root);
-            for entry in entries.values() {
-                build_filesystem_map(entry, ino_to_entry);
-            }
+/// Walks (creating as needed) the directories of a `\`-separated Windows
+/// path and inserts a leaf built by `make_leaf` at its end.
+fn insert_at_path<'a>(
+    root: &mut FSEntry<'a>,
+    ino_counter: &mut u64,
+    full_path: &str,
+    make_leaf: impl FnOnce(u64) -> FSEntry<'a>,
+) {
+    // Root-level entries (e.g. "big.bin") have no backslash at all; treat
+    // that as an empty directory path instead of dropping the entry.
+    let (dir_path, name) = full_path.rsplit_once('\\').unwrap_or(("", full_path));
+
+    let mut prev_dir = root;
+
+    for dir_name in dir_path.split('\\').filter(|s| !s.is_empty()) {
+        let FSEntry::Directory { entries, .. } = prev_dir else { break };
+
+        if !entries.contains_key(dir_name) {
+            entries.insert(dir_name.to_owned(), FSEntry::dir(*ino_counter));
+            *ino_counter += 1;
         }
-        FSEntry::File { ino, .. } => {
-            ino_to_entry.insert(*ino, root);
+
+        prev_dir = entries.get_mut(dir_name).unwrap();
+    }
+
+    let FSEntry::Directory { entries, .. } = prev_dir else { return };
+
+    let ino = *ino_counter;
+    *ino_counter += 1;
+    entries.insert(name.to_owned(), make_leaf(ino));
+}
+
+fn build_filesystem_map<'a>(root: &'a FSEntry, ino_to_entry: &mut HashMap<u64, &'a FSEntry<'a>>) {
+    ino_to_entry.insert(root.ino(), root);
+
+    if let FSEntry::Directory { entries, .. } = root {
+        for entry in entries.values() {
+            build_filesystem_map(entry, ino_to_entry);
         }
     }
 }
@@ -188,23 +391,6 @@ fn build_filesystem_map<'a>(root: &'a FSEntry, ino_to_entry: &mut HashMap<u64, &
 impl FSEntry<'_> {
     fn attrs(&self) -> FileAttr {
         match self {
-            FSEntry::File { ino, info, .. } => FileAttr {
-                ino: *ino,
-                size: info.info.size,
-                blocks: (info.info.size + BLOCK_SIZE - 1) / BLOCK_SIZE,
-                atime: info.info.time,
-                mtime: info.info.time,
-                ctime: info.info.time,
-                crtime: info.info.time,
-                kind: FileType::RegularFile,
-                perm: 0o644,
-                nlink: 1,
-                uid: 501,
-                gid: 20,
-                rdev: 0,
-                flags: 0,
-                blksize: BLOCK_SIZE as u32,
-            },
             FSEntry::Directory { ino, .. } => FileAttr {
                 ino: *ino,
                 size: 0,
@@ -222,6 +408,33 @@ impl FSEntry<'_> {
                 flags: 0,
                 blksize: 0, // ?
             },
+            _ => {
+                let info = self.latest();
+
+                FileAttr {
+                    ino: self.ino(),
+                    size: info.info.size,
+                    blocks: (info.info.size + BLOCK_SIZE - 1) / BLOCK_SIZE,
+                    atime: info.info.time,
+                    mtime: info.info.time,
+                    ctime: info.info.time,
+                    crtime: info.info.time,
+                    kind: self.filetype(),
+                    perm: (info.info.mode & 0o7777) as u16,
+                    nlink: 1,
+                    uid: 501,
+                    gid: 20,
+                    // Only device nodes have a meaningful major/minor;
+                    // everything else reports 0 even if the archive's
+                    // Unix extra field happened to carry one.
+                    rdev: match self.filetype() {
+                        FileType::CharDevice | FileType::BlockDevice => info.info.rdev,
+                        _ => 0,
+                    },
+                    flags: 0,
+                    blksize: BLOCK_SIZE as u32,
+                }
+            }
         }
     }
 
@@ -229,83 +442,89 @@ impl FSEntry<'_> {
         match self {
             FSEntry::File { .. } => FileType::RegularFile,
             FSEntry::Directory { .. } => FileType::Directory,
+            FSEntry::Symlink { .. } => FileType::Symlink,
+            FSEntry::CharDevice { .. } => FileType::CharDevice,
+            FSEntry::BlockDevice { .. } => FileType::BlockDevice,
+            FSEntry::NamedPipe { .. } => FileType::NamedPipe,
+            FSEntry::Socket { .. } => FileType::Socket,
         }
     }
-}
-
-#[derive(Debug)]
-struct OpenedArchive {
-    offset: usize,
-    content: OwnedZipFileBytes,
-}
 
-impl OpenedArchive {
-    pub fn open(source: &Source) -> Self {
-        Self {
-            offset: 0,
-            content: OwnedZipFileBytes::open(source.archive, source.file_id),
+    /// The names of the provenance xattrs this entry exposes. Empty for
+    /// directories, which have no `Source` to report.
+    fn xattr_names(&self) -> &'static [&'static str] {
+        match self {
+            FSEntry::Directory { .. } => &[],
+            _ => XATTR_NAMES,
         }
     }
 
-    pub fn is_viable(&self, offset: usize) -> bool {
-        offset >= self.offset
-    }
+    /// The value of a single provenance xattr, or `None` if `name` isn't
+    /// one this entry exposes (including every name, for a directory).
+    fn xattr(&self, name: &str) -> Option<Vec<u8>> {
+        if matches!(self, FSEntry::Directory { .. }) {
+            return None;
+        }
 
-    pub fn read_bytes(&mut self, offset: usize, size: usize) -> Vec<u8> {
-        let to_skip = offset - self.offset;
-        self.offset = offset + size;
-
-        self.content.with_bytes_mut(|bytes| {
-            bytes
-                .skip(to_skip)
-                .take(size)
-                .filter_map(Result::ok)
-                .collect()
-        })
-    }
-}
+        let info = self.latest();
+
+        let value = match name {
+            XATTR_ARCHIVE => fs::canonicalize(info.source.archive)
+                .unwrap_or_else(|_| info.source.archive.to_path_buf())
+                .to_string_lossy()
+                .into_owned(),
+            XATTR_FILE_ID => info.source.file_id.to_string(),
+            XATTR_MTIME => info
+                .info
+                .time
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs()
+                .to_string(),
+            XATTR_CRC32 => format!("{:08x}", info.info.crc32),
+            XATTR_COMPRESSED_SIZE => info.info.compressed_size.to_string(),
+            _ => return None,
+        };
 
-impl std::fmt::Debug for OwnedZipFileBytes {
-    fn fmt(&self, _: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
-        Ok(())
+        Some(value.into_bytes())
     }
 }
 
-#[self_referencing]
-struct OwnedZipFileBytes {
-    archive: ZipArchive<std::fs::File>,
-    #[borrows(mut archive)]
-    #[not_covariant]
-    bytes: std::io::Bytes<ZipFile<'this>>,
-}
-
-impl OwnedZipFileBytes {
-    pub fn open(archive: &Path, file_id: usize) -> Self {
-        let file = std::fs::File::open(archive).unwrap();
-        let archive = ZipArchive::new(file).unwrap();
-        OwnedZipFileBytesBuilder {
-            archive,
-            bytes_builder: |archive| archive.by_index(file_id).unwrap().bytes(),
-        }
-        .build()
+/// Decompresses an entire archive member into memory, or into a spilled
+/// temp file if it's larger than `memory_threshold`. DEFLATE streams
+/// aren't seekable, so this is done once per inode and the result is
+/// served to every `read` as a direct slice.
+fn decompress_member(archive: &Path, file_id: usize, size: u64, memory_threshold: u64) -> CachedContent {
+    let file = std::fs::File::open(archive).unwrap();
+    let mut zip = ZipArchive::new(BufReader::new(file)).unwrap();
+    let mut entry = zip.by_index(file_id).unwrap();
+
+    if size <= memory_threshold {
+        let mut buf = Vec::with_capacity(size as usize);
+        entry.read_to_end(&mut buf).unwrap();
+        CachedContent::Memory(buf)
+    } else {
+        let mut tmp = tempfile::NamedTempFile::new().unwrap();
+        std::io::copy(&mut entry, &mut tmp).unwrap();
+        CachedContent::Spilled { file: tmp, size }
     }
 }
 
 struct WinbackupFS<'a> {
     filesystem: HashMap<u64, &'a FSEntry<'a>>,
-    handlers: HashMap<u64, OpenedArchive>,
-    handlers_counter: u64,
+    cache: DecompressedCache,
+    memory_threshold: u64,
 }
 
 impl<'a> WinbackupFS<'a> {
-    fn from_tree(root: &'a FSEntry<'a>) -> Self {
+    fn from_tree(root: &'a FSEntry<'a>, cache_bytes: u64, memory_threshold: u64) -> Self {
         let mut ino_to_entry: HashMap<u64, &FSEntry> = HashMap::new();
         build_filesystem_map(root, &mut ino_to_entry);
 
         WinbackupFS {
             filesystem: ino_to_entry,
-            handlers: HashMap::new(),
-            handlers_counter: 0,
+            cache: DecompressedCache::new(cache_bytes),
+            memory_threshold,
         }
     }
 }
@@ -341,54 +560,124 @@ impl Filesystem for WinbackupFS<'_> {
         &mut self,
         _req: &Request<'_>,
         _ino: u64,
-        fh: u64,
+        _fh: u64,
         _flags: i32,
         _lock_owner: Option<u64>,
         _flush: bool,
         reply: ReplyEmpty,
     ) {
-        self.handlers.remove(&fh);
         reply.ok();
     }
 
     fn open(&mut self, _req: &Request<'_>, _ino: u64, _flags: i32, reply: ReplyOpen) {
-        reply.opened(self.handlers_counter, 0);
-        self.handlers_counter += 1;
+        // `read()` is handed the inode directly, so the file handle carries
+        // no state of its own.
+        reply.opened(0, 0);
     }
 
     fn read(
         &mut self,
         _req: &Request,
         ino: u64,
-        fh: u64,
+        _fh: u64,
         offset: i64,
         size: u32,
         _flags: i32,
         _lock: Option<u64>,
         reply: ReplyData,
     ) {
-        let archive = match self.handlers.entry(fh) {
-            Entry::Occupied(entry) if entry.get().is_viable(offset as usize) => {
-                &mut *entry.into_mut()
-            }
-            entry => {
-                let archive = {
-                    let Some(FSEntry::File{ info, .. }) = self.filesystem.get(&ino) else {
-                        reply.error(ENOENT);
-                        return;
-                    };
-
-                    OpenedArchive::open(&info.source)
-                };
-
-                entry.insert_entry(archive).into_mut()
-            }
+        let Some(entry) = self.filesystem.get(&ino) else {
+            reply.error(ENOENT);
+            return;
         };
 
-        let bytes = archive.read_bytes(offset as usize, size as usize);
+        if !matches!(entry, FSEntry::File { .. }) {
+            reply.error(ENOENT);
+            return;
+        }
+
+        let info = entry.latest();
+        let archive = info.source.archive;
+        let file_id = info.source.file_id;
+        let file_size = info.info.size;
+        let memory_threshold = self.memory_threshold;
+
+        let content = self.cache.get_or_insert_with(ino, move || {
+            decompress_member(archive, file_id, file_size, memory_threshold)
+        });
+
+        let bytes = content.read_at(offset as u64, size);
         reply.data(&bytes);
     }
 
+    fn readlink(&mut self, _req: &Request, ino: u64, reply: ReplyData) {
+        let Some(entry) = self.filesystem.get(&ino) else {
+            reply.error(ENOENT);
+            return;
+        };
+
+        if !matches!(entry, FSEntry::Symlink { .. }) {
+            reply.error(EINVAL);
+            return;
+        }
+
+        // A symlink's "contents" in the ZIP is the link target's bytes,
+        // so this reuses the same decompress-once-and-cache path as read.
+        let info = entry.latest();
+        let archive = info.source.archive;
+        let file_id = info.source.file_id;
+        let file_size = info.info.size;
+        let memory_threshold = self.memory_threshold;
+
+        let content = self.cache.get_or_insert_with(ino, move || {
+            decompress_member(archive, file_id, file_size, memory_threshold)
+        });
+
+        let target = content.read_at(0, file_size as u32);
+        reply.data(&target);
+    }
+
+    fn getxattr(&mut self, _req: &Request<'_>, ino: u64, name: &OsStr, size: u32, reply: ReplyXattr) {
+        let Some(entry) = self.filesystem.get(&ino) else {
+            reply.error(ENOENT);
+            return;
+        };
+
+        let Some(value) = name.to_str().and_then(|name| entry.xattr(name)) else {
+            reply.error(ENODATA);
+            return;
+        };
+
+        if size == 0 {
+            reply.size(value.len() as u32);
+        } else if value.len() as u32 > size {
+            reply.error(ERANGE);
+        } else {
+            reply.data(&value);
+        }
+    }
+
+    fn listxattr(&mut self, _req: &Request<'_>, ino: u64, size: u32, reply: ReplyXattr) {
+        let Some(entry) = self.filesystem.get(&ino) else {
+            reply.error(ENOENT);
+            return;
+        };
+
+        let list: Vec<u8> = entry
+            .xattr_names()
+            .iter()
+            .flat_map(|name| name.bytes().chain(std::iter::once(0)))
+            .collect();
+
+        if size == 0 {
+            reply.size(list.len() as u32);
+        } else if list.len() as u32 > size {
+            reply.error(ERANGE);
+        } else {
+            reply.data(&list);
+        }
+    }
+
     fn readdir(
         &mut self,
         _req: &Request,
@@ -403,11 +692,7 @@ impl Filesystem for WinbackupFS<'_> {
         };
 
         for (i, (name, entry)) in entries.iter().enumerate().skip(offset as usize) {
-            let ino = match entry {
-                FSEntry::File { ino, .. } | FSEntry::Directory { ino, .. } => ino,
-            };
-
-            if reply.add(*ino, (i + 1) as i64, entry.filetype(), name) {
+            if reply.add(entry.ino(), (i + 1) as i64, entry.filetype(), name) {
                 break;
             }
         }
@@ -417,8 +702,32 @@ impl Filesystem for WinbackupFS<'_> {
 }
 
 fn main() {
-    let archives_glob = env::args().nth(1).expect("Provide a glob for the backup archives list");
-    let mountpoint = env::args().nth(2).expect("Provide a mount point");
+    let mut args: Vec<String> = env::args().skip(1).collect();
+
+    let reindex = if let Some(pos) = args.iter().position(|arg| arg == "--reindex") {
+        args.remove(pos);
+        true
+    } else {
+        false
+    };
+
+    let versions_mode = if let Some(pos) = args.iter().position(|arg| arg == "--versions") {
+        args.remove(pos);
+        true
+    } else {
+        false
+    };
+
+    let cache_bytes = take_flag_value(&mut args, "--cache-bytes")
+        .map(|v| v.parse().expect("--cache-bytes must be a number"))
+        .unwrap_or(DEFAULT_CACHE_BYTES);
+
+    let memory_threshold = take_flag_value(&mut args, "--memory-threshold")
+        .map(|v| v.parse().expect("--memory-threshold must be a number"))
+        .unwrap_or(DEFAULT_MEMORY_THRESHOLD);
+
+    let archives_glob = args.first().expect("Provide a glob for the backup archives list").clone();
+    let mountpoint = args.get(1).expect("Provide a mount point").clone();
 
     let sources: Vec<PathBuf> = glob::glob(&archives_glob)
         .unwrap()
@@ -429,9 +738,90 @@ fn main() {
         filename_encoding: encoding_rs::IBM866,
     };
 
-    let tree = winbackup.parse_multiple_archives(&sources);
-    let fs = WinbackupFS::from_tree(&tree);
+    let index_path = PathBuf::from(format!("{mountpoint}.winbackup-index.zst"));
+
+    let tree = if !reindex {
+        index::load(&index_path, &sources, versions_mode)
+    } else {
+        None
+    };
+
+    let tree = match tree {
+        Some(tree) => tree,
+        None => {
+            let tree = winbackup.parse_multiple_archives(&sources, versions_mode);
+            if let Err(()) = index::save(&index_path, &tree, &sources, versions_mode) {
+                eprintln!("Warning: failed to write index to {}", index_path.display());
+            }
+            tree
+        }
+    };
+
+    let fs = WinbackupFS::from_tree(&tree, cache_bytes, memory_threshold);
 
     let options = vec![MountOption::RO, MountOption::FSName("winbackup-fuse".to_string())];
     fuser::mount2(fs, mountpoint, &options).unwrap();
 }
+
+/// Removes `--flag <value>` from `args` and returns `value`, if present.
+fn take_flag_value(args: &mut Vec<String>, flag: &str) -> Option<String> {
+    let pos = args.iter().position(|arg| arg == flag)?;
+    args.remove(pos);
+    if pos < args.len() {
+        Some(args.remove(pos))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Synthetic Info-ZIP "UX" extra field (tag `0x5855`), hand-built per
+    /// the format's atime/mtime/uid/gid/major/minor layout for a
+    /// character device entry (major 1, minor 3, i.e. `/dev/null`) —
+    /// not captured from a real archive.
+    fn unix_extra_field_for_dev_null() -> Vec<u8> {
+        let mut field = Vec::new();
+        field.extend_from_slice(&UNIX_EXTRA_FIELD_TAG.to_le_bytes());
+        field.extend_from_slice(&20u16.to_le_bytes()); // size: 12 fixed + 8 device
+        field.extend_from_slice(&1_767_225_600u32.to_le_bytes()); // atime
+        field.extend_from_slice(&1_767_225_600u32.to_le_bytes()); // mtime
+        field.extend_from_slice(&0u16.to_le_bytes()); // uid
+        field.extend_from_slice(&0u16.to_le_bytes()); // gid
+        field.extend_from_slice(&1u32.to_le_bytes()); // major
+        field.extend_from_slice(&3u32.to_le_bytes()); // minor
+        field
+    }
+
+    #[test]
+    fn reads_major_minor_from_ux_field() {
+        let extra = unix_extra_field_for_dev_null();
+        assert_eq!(unix_rdev_from_extra(&extra), (1 << 8) | 3);
+    }
+
+    #[test]
+    fn skips_unrelated_extra_fields_before_ux() {
+        let mut extra = vec![0xAA, 0x55, 0x02, 0x00, 0x00, 0x00]; // unrelated 2-byte record
+        extra.extend_from_slice(&unix_extra_field_for_dev_null());
+        assert_eq!(unix_rdev_from_extra(&extra), (1 << 8) | 3);
+    }
+
+    #[test]
+    fn returns_zero_when_ux_field_has_no_device_bytes() {
+        // Old-style UX field with only atime/mtime/uid/gid (no device data),
+        // as written for regular files.
+        let mut extra = Vec::new();
+        extra.extend_from_slice(&UNIX_EXTRA_FIELD_TAG.to_le_bytes());
+        extra.extend_from_slice(&12u16.to_le_bytes());
+        extra.extend_from_slice(&[0u8; 12]);
+        assert_eq!(unix_rdev_from_extra(&extra), 0);
+    }
+
+    #[test]
+    fn returns_zero_for_absent_or_truncated_extra() {
+        assert_eq!(unix_rdev_from_extra(&[]), 0);
+        assert_eq!(unix_rdev_from_extra(&[0x55, 0x58, 0xFF, 0xFF]), 0);
+    }
+}